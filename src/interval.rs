@@ -2,6 +2,8 @@ use image::GenericImageView;
 
 use std::ops::{Bound, Range, RangeBounds};
 
+use crate::SortAxis;
+
 #[derive(Debug)]
 pub struct IntervalSet(Vec<Range<usize>>);
 
@@ -14,9 +16,10 @@ impl IntervalSet {
         IntervalSet(vec![0..size])
     }
 
-    pub fn intervals_from_image<I: GenericImageView>(image: &I) -> Vec<IntervalSet> {
-        (0..image.height())
-            .map(|_| IntervalSet::new(image.width() as usize))
+    pub fn intervals_from_image<I: GenericImageView>(image: &I, axis: SortAxis) -> Vec<IntervalSet> {
+        let line_len = axis.line_len(image) as usize;
+        (0..axis.line_count(image))
+            .map(|_| IntervalSet::new(line_len))
             .collect()
     }
 
@@ -98,13 +101,18 @@ impl IntervalSet {
     }
 }
 
-pub fn mask(intervals: &mut [IntervalSet], mask: &image::GrayImage) {
-    for (row, set) in mask.rows().zip(intervals) {
-        let mut pixels = row.enumerate();
-        while let Some((last_white, _)) = pixels.find(|(_, pixel)| **pixel == image::Luma([255])) {
+pub fn mask(intervals: &mut [IntervalSet], mask: &image::GrayImage, axis: SortAxis) {
+    let line_len = axis.line_len(mask);
+    for (line, set) in (0..axis.line_count(mask)).zip(intervals) {
+        let pixel_at = |i: u32| match axis {
+            SortAxis::Horizontal => *mask.get_pixel(i, line),
+            SortAxis::Vertical => *mask.get_pixel(line, i),
+        };
+        let mut pixels = (0..line_len).map(pixel_at).enumerate();
+        while let Some((last_white, _)) = pixels.find(|(_, pixel)| *pixel == image::Luma([255])) {
             set.split_at(last_white);
             let first_white =
-                if let Some((pos, _)) = pixels.find(|(_, pixel)| **pixel == image::Luma([0])) {
+                if let Some((pos, _)) = pixels.find(|(_, pixel)| *pixel == image::Luma([0])) {
                     pos
                 } else {
                     if let Some((_, to_remove)) = set.split_at(last_white) {
@@ -132,20 +140,23 @@ pub fn random(intervals: &mut [IntervalSet], lower: usize, upper: usize) {
     }
 }
 
-pub fn threshold<P, I>(intervals: &mut [IntervalSet], image: &I, low: u8, high: u8)
+// low/high are normalized to 0.0..=1.0 so callers don't need to know the
+// mask is built from an 8-bit grayscale pixel
+pub fn threshold<P, I>(intervals: &mut [IntervalSet], image: &I, axis: SortAxis, low: f32, high: f32)
 where
     P: image::Pixel<Subpixel = u8>,
     I: GenericImageView<Pixel = P>,
 {
     let mut gray = image::imageops::colorops::grayscale(image);
     for pixel in gray.pixels_mut() {
-        if (low..high).contains(&pixel.0[0]) {
+        let normalized = pixel.0[0] as f32 / 255.0;
+        if (low..high).contains(&normalized) {
             *pixel = image::Luma([255]);
         } else {
             *pixel = image::Luma([0]);
         }
     }
-    mask(intervals, &gray);
+    mask(intervals, &gray, axis);
 }
 
 pub fn split_equal(intervals: &mut [IntervalSet], part_count: usize) {
@@ -162,6 +173,7 @@ pub fn split_equal(intervals: &mut [IntervalSet], part_count: usize) {
 pub fn edges_canny<P, I>(
     intervals: &mut [IntervalSet],
     image: &I,
+    axis: SortAxis,
     low_thresh: f32,
     high_thresh: f32,
 ) where
@@ -170,5 +182,5 @@ pub fn edges_canny<P, I>(
 {
     let gray = image::imageops::colorops::grayscale(image);
     let edges = imageproc::edges::canny(&gray, low_thresh, high_thresh);
-    mask(intervals, &edges);
+    mask(intervals, &edges, axis);
 }
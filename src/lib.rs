@@ -1,37 +1,235 @@
-use image::{GenericImage, GenericImageView, Pixel};
+use image::{GenericImage, GenericImageView, Pixel, Primitive};
 
 pub mod sorting;
 
 pub mod interval;
 use self::interval::IntervalSet;
 
-pub fn sort_image<I, P, SF>(image: &mut I, intervals: Vec<IntervalSet>, sorting_function: SF)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortAxis {
+    Horizontal,
+    Vertical,
+}
+
+impl SortAxis {
+    pub fn line_count<I: GenericImageView>(self, image: &I) -> u32 {
+        match self {
+            SortAxis::Horizontal => image.height(),
+            SortAxis::Vertical => image.width(),
+        }
+    }
+
+    pub fn line_len<I: GenericImageView>(self, image: &I) -> u32 {
+        match self {
+            SortAxis::Horizontal => image.width(),
+            SortAxis::Vertical => image.height(),
+        }
+    }
+}
+
+pub trait SortKey<P> {
+    fn key(&mut self, pixel: &P) -> u64;
+
+    fn max_key(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl<P, F> SortKey<P> for F
+where
+    F: FnMut(&P) -> u64,
+{
+    fn key(&mut self, pixel: &P) -> u64 {
+        self(pixel)
+    }
+}
+
+impl<P, F> SortKey<P> for (F, u64)
 where
+    F: FnMut(&P) -> u64,
+{
+    fn key(&mut self, pixel: &P) -> u64 {
+        (self.0)(pixel)
+    }
+
+    fn max_key(&self) -> Option<u64> {
+        Some(self.1)
+    }
+}
+
+impl<P> SortKey<P> for Box<dyn SortKey<P>> {
+    fn key(&mut self, pixel: &P) -> u64 {
+        (**self).key(pixel)
+    }
+
+    fn max_key(&self) -> Option<u64> {
+        (**self).max_key()
+    }
+}
+
+// shared between `sort_image` and `par_sort_image` so both pick the same
+// counting-sort fast path when the key's range is known
+fn sort_scratch<P, SF>(
+    scratch: &mut Vec<P>,
+    counts: &mut Vec<u32>,
+    output: &mut Vec<P>,
+    sorting_function: &mut SF,
+) where
+    P: Pixel,
+    SF: SortKey<P>,
+{
+    // only worth it once k is in the same ballpark as n; otherwise a huge
+    // bound (e.g. 16-bit lightness) would resize/zero-fill `counts` far
+    // beyond what this interval needs
+    const COUNTING_SORT_DENSITY: u64 = 4;
+    let max_key = sorting_function
+        .max_key()
+        .filter(|&max| max <= scratch.len() as u64 * COUNTING_SORT_DENSITY);
+    match max_key {
+        Some(max) => {
+            // counting sort: O(n + k) and stable, reusing `counts` and
+            // `output` across intervals to avoid reallocating them.
+            counts.clear();
+            counts.resize(max as usize + 2, 0u32);
+            for pixel in scratch.iter() {
+                counts[sorting_function.key(pixel) as usize + 1] += 1;
+            }
+            for i in 1..counts.len() {
+                counts[i] += counts[i - 1];
+            }
+            output.clear();
+            output.extend_from_slice(scratch);
+            for pixel in scratch.drain(..) {
+                let offset = &mut counts[sorting_function.key(&pixel) as usize];
+                output[*offset as usize] = pixel;
+                *offset += 1;
+            }
+            scratch.extend_from_slice(output);
+        }
+        // pixel ordering within equal keys doesn't matter visually, so
+        // an unstable sort avoids the stable sort's merge allocation
+        None => scratch.sort_unstable_by_key(|pixel| sorting_function.key(pixel)),
+    }
+}
+
+// `reverse` flips which end of each interval receives the smallest-key
+// pixel, since a 90/270 rotation reads its column in the opposite
+// direction depending on which way it was rotated
+pub fn sort_image<I, P, SF>(
+    image: &mut I,
+    axis: SortAxis,
+    reverse: bool,
+    intervals: Vec<IntervalSet>,
+    mut sorting_function: SF,
+) where
     I: GenericImage + GenericImageView<Pixel = P>,
-    P: Pixel<Subpixel = u8>,
-    SF: FnMut(&P) -> u32 + Clone,
+    P: Pixel,
+    P::Subpixel: Primitive,
+    SF: SortKey<P>,
 {
-    // allocate buffer outside to prevent frequent reallocations
+    // allocate buffers outside to prevent frequent reallocations
     let mut scratch = Vec::new();
-    for (row, set) in intervals
-        .into_iter()
-        .enumerate()
-        .take(image.height() as usize)
-    {
+    let mut counts = Vec::new();
+    let mut output = Vec::new();
+    let line_count = axis.line_count(image);
+    for (line, set) in intervals.into_iter().enumerate().take(line_count as usize) {
         for range in set.iter() {
-            let mut sub = image.sub_image(
-                range.start as u32,
-                row as u32,
-                range.end as u32 - range.start as u32,
-                1,
-            );
+            let len = range.end as u32 - range.start as u32;
+            let mut sub = match axis {
+                SortAxis::Horizontal => image.sub_image(range.start as u32, line as u32, len, 1),
+                SortAxis::Vertical => image.sub_image(line as u32, range.start as u32, 1, len),
+            };
             scratch.extend(sub.pixels().map(|(_, _, pixel)| pixel));
-            scratch.sort_by_key(sorting_function.clone());
-            for (x, pixel) in scratch.drain(..).enumerate() {
+            sort_scratch(&mut scratch, &mut counts, &mut output, &mut sorting_function);
+            for (i, pixel) in scratch.drain(..).enumerate() {
+                let i = if reverse { len - 1 - i as u32 } else { i as u32 };
                 // SAFETY: if we were to put a pixel outside of its bounds we would've panicked at the pixels() collection
-                unsafe { sub.unsafe_put_pixel(x as u32, 0, pixel) };
+                match axis {
+                    SortAxis::Horizontal => unsafe { sub.unsafe_put_pixel(i, 0, pixel) },
+                    SortAxis::Vertical => unsafe { sub.unsafe_put_pixel(0, i, pixel) },
+                }
                 //unsafe { sub.unsafe_put_pixel(x as u32, 0, Pixel::from_channels(255, 0, 0, 255)) };
             }
         }
     }
 }
+
+// SAFETY: callers only read/write through the indices of their own line,
+// and distinct lines never share a subpixel index, so aliasing this pointer
+// across tasks is data-race free.
+#[cfg(feature = "rayon")]
+#[derive(Clone, Copy)]
+struct BufferPtr<S>(*mut S);
+
+#[cfg(feature = "rayon")]
+unsafe impl<S> Send for BufferPtr<S> {}
+#[cfg(feature = "rayon")]
+unsafe impl<S> Sync for BufferPtr<S> {}
+
+// parallel counterpart to `sort_image`; takes a concrete `ImageBuffer`
+// rather than `GenericImage` since each task needs its own pointer into the
+// shared subpixel buffer instead of `sub_image`'s single mutable borrow
+#[cfg(feature = "rayon")]
+pub fn par_sort_image<P, SF>(
+    image: &mut image::ImageBuffer<P, Vec<P::Subpixel>>,
+    axis: SortAxis,
+    reverse: bool,
+    intervals: Vec<IntervalSet>,
+    sorting_function: SF,
+) where
+    P: Pixel + Send + Sync,
+    P::Subpixel: Primitive + Send + Sync,
+    SF: SortKey<P> + Clone + Send + Sync,
+{
+    use rayon::prelude::*;
+
+    let width = image.width() as usize;
+    let channels = P::CHANNEL_COUNT as usize;
+    let line_count = axis.line_count(image) as usize;
+    let buffer = BufferPtr(image.as_mut_ptr());
+
+    let subpixel_index = move |x: usize, y: usize| (y * width + x) * channels;
+
+    intervals
+        .into_par_iter()
+        .enumerate()
+        .take(line_count)
+        .for_each_init(
+            || (Vec::new(), Vec::new(), Vec::new(), sorting_function.clone()),
+            |(scratch, counts, output, sorting_function), (line, set)| {
+                for range in set.iter() {
+                    scratch.extend(range.clone().map(|i| {
+                        let (x, y) = match axis {
+                            SortAxis::Horizontal => (i, line),
+                            SortAxis::Vertical => (line, i),
+                        };
+                        // SAFETY: see the comment on `BufferPtr`.
+                        let subpixels = unsafe {
+                            std::slice::from_raw_parts(
+                                buffer.0.add(subpixel_index(x, y)),
+                                channels,
+                            )
+                        };
+                        *P::from_slice(subpixels)
+                    }));
+                    sort_scratch(scratch, counts, output, sorting_function);
+                    let len = range.end - range.start;
+                    for (i, pixel) in scratch.drain(..).enumerate() {
+                        let i = if reverse { len - 1 - i } else { i };
+                        let (x, y) = match axis {
+                            SortAxis::Horizontal => (range.start + i, line),
+                            SortAxis::Vertical => (line, range.start + i),
+                        };
+                        // SAFETY: see the comment on `BufferPtr`.
+                        let subpixels = unsafe {
+                            std::slice::from_raw_parts_mut(
+                                buffer.0.add(subpixel_index(x, y)),
+                                channels,
+                            )
+                        };
+                        subpixels.copy_from_slice(pixel.channels());
+                    }
+                }
+            },
+        );
+}
@@ -6,6 +6,7 @@ use std::str;
 
 use pixelsort::interval::{self, IntervalSet};
 use pixelsort::sorting;
+use pixelsort::SortKey;
 
 #[derive(Clone, Copy)]
 pub enum SortingMode {
@@ -13,18 +14,35 @@ pub enum SortingMode {
     Intensity,
     Minimum,
     Maximum,
+    Hue,
+    Saturation,
 }
 
 impl SortingMode {
-    pub fn function<P>(self) -> fn(&P) -> u32
+    // The built-in modes all have a known upper bound, so they're paired
+    // with that bound to let `sort_scratch` take the counting-sort fast path
+    // for intervals long enough relative to it; shorter intervals still use
+    // a comparison sort.
+    pub fn function<P>(self, hue_origin: u32) -> Box<dyn SortKey<P>>
     where
-        P: image::Pixel<Subpixel = u8>,
+        P: image::Pixel + 'static,
+        P::Subpixel: image::Primitive,
     {
+        let max_subpixel = sorting::max_value::<P::Subpixel>();
         match self {
-            SortingMode::Lightness => sorting::lightness,
-            SortingMode::Intensity => sorting::intensity,
-            SortingMode::Minimum => sorting::chan_max,
-            SortingMode::Maximum => sorting::chan_min,
+            SortingMode::Lightness => {
+                Box::new((sorting::lightness as fn(&P) -> u64, max_subpixel))
+            }
+            SortingMode::Intensity => Box::new((
+                sorting::intensity as fn(&P) -> u64,
+                max_subpixel * P::CHANNEL_COUNT as u64,
+            )),
+            SortingMode::Minimum => Box::new((sorting::chan_max as fn(&P) -> u64, max_subpixel)),
+            SortingMode::Maximum => Box::new((sorting::chan_min as fn(&P) -> u64, max_subpixel)),
+            SortingMode::Hue => {
+                Box::new((move |pixel: &P| sorting::hue(pixel, hue_origin), 3599))
+            }
+            SortingMode::Saturation => Box::new((sorting::saturation as fn(&P) -> u64, 255)),
         }
     }
 }
@@ -37,6 +55,8 @@ impl str::FromStr for SortingMode {
             "intensity" => Ok(SortingMode::Intensity),
             "minimum" => Ok(SortingMode::Minimum),
             "maximum" => Ok(SortingMode::Maximum),
+            "hue" => Ok(SortingMode::Hue),
+            "saturation" => Ok(SortingMode::Saturation),
             _ => Err(String::from(s)),
         }
     }
@@ -115,12 +135,10 @@ fn main() {
             arg_rotation(),
             arg_num(),
             arg_sorting(),
+            arg_hue_origin(),
         ])
         .get_matches();
     let input = Path::new(matches.value_of_os("input").unwrap());
-    let mut image = image::open(input)
-        .expect("failed to read input image")
-        .to_rgba();
     let output = matches
         .value_of_os("output")
         .map(PathBuf::from)
@@ -132,74 +150,151 @@ fn main() {
             input.with_extension(["sorted", ".", extension].concat())
         });
     let rotate = Rotation::from_str(matches.value_of("rotation").unwrap()).unwrap();
-
-    //rotate
-    match rotate {
-        Rotation::Quarter => image = imageops::rotate90(&image),
-        Rotation::Half => image = imageops::rotate180(&image),
-        Rotation::NegQuarter => image = imageops::rotate270(&image),
-        Rotation::Zero => (),
-    }
-    let sorting_func = SortingMode::from_str(matches.value_of("sorting").unwrap())
+    // a 90/270 rotation only changes the axis pixels are sorted along, so it
+    // is handled by `SortAxis` instead of physically rotating the buffer; a
+    // 180 rotation reverses the sort direction and still needs to rotate.
+    // 90 and 270 rotate a column into a row from opposite ends, so they also
+    // need opposite sort directions to match the old rotate-sort-rotate-back
+    // behavior.
+    let axis = match rotate {
+        Rotation::Quarter | Rotation::NegQuarter => pixelsort::SortAxis::Vertical,
+        Rotation::Zero | Rotation::Half => pixelsort::SortAxis::Horizontal,
+    };
+    let reverse = matches!(rotate, Rotation::Quarter);
+    let hue_origin = matches
+        .value_of("hue_origin")
         .unwrap()
-        .function();
+        .parse()
+        .expect("hue-origin was not an integer");
+    let sorting_mode = SortingMode::from_str(matches.value_of("sorting").unwrap()).unwrap();
     let interval_func =
         IntervalFunction::from_str(matches.value_of("interval_func").unwrap()).unwrap();
+    let mask_path = matches.value_of_os("mask").map(Path::new);
+    let upper = matches.value_of("upper").unwrap_or_default();
+    let lower = matches.value_of("lower").unwrap_or_default();
 
-    let mut intervals = IntervalSet::intervals_from_image(&image);
-    if let Some(mask_path) = matches.value_of_os("mask").map(Path::new) {
-        let mut mask = image::open(mask_path).unwrap().to_luma();
-        match rotate {
-            Rotation::Quarter => mask = imageops::rotate90(&mask),
-            Rotation::Half => mask = imageops::rotate180(&mask),
-            Rotation::NegQuarter => mask = imageops::rotate270(&mask),
-            Rotation::Zero => (),
+    let dynamic = image::open(input).expect("failed to read input image");
+    // `threshold`/`edge` derive their mask from an 8-bit grayscale of the
+    // image itself (`imageops::colorops::grayscale`/`imageproc::edges::canny`
+    // are u8-only), so they aren't available on a genuinely 16-bit source;
+    // every other interval function only looks at line length, and sorting
+    // itself is generic, so 16-bit images keep their precision end to end.
+    let is_16_bit = matches!(
+        dynamic,
+        image::DynamicImage::ImageLuma16(_)
+            | image::DynamicImage::ImageLumaA16(_)
+            | image::DynamicImage::ImageRgb16(_)
+            | image::DynamicImage::ImageRgba16(_)
+    );
+    if is_16_bit {
+        let mut image = dynamic.to_rgba16();
+        if let Rotation::Half = rotate {
+            image = imageops::rotate180(&image);
+        }
+        let mut intervals = IntervalSet::intervals_from_image(&image, axis);
+        apply_mask_file(&mut intervals, mask_path, rotate, axis);
+        match interval_func {
+            IntervalFunction::Full => (),
+            IntervalFunction::SplitEqual => interval::split_equal(
+                &mut intervals,
+                matches
+                    .value_of("num")
+                    .unwrap()
+                    .parse()
+                    .expect("num was not an integer"),
+            ),
+            #[cfg(feature = "imageproc")]
+            IntervalFunction::Edges => {
+                panic!("edge detection requires an 8-bit image; this image is 16-bit")
+            }
+            #[cfg(feature = "rand")]
+            IntervalFunction::Random => interval::random(
+                &mut intervals,
+                lower.parse().expect("lower was not an integer"),
+                upper.parse().expect("upper was not an integer"),
+            ),
+            IntervalFunction::Threshold => {
+                panic!("threshold requires an 8-bit image; this image is 16-bit")
+            }
         }
-        interval::mask(&mut intervals, &mask);
+        let sorting_func = sorting_mode.function::<image::Rgba<u16>>(hue_origin);
+        finish(image, axis, reverse, rotate, intervals, sorting_func, &output);
+    } else {
+        let mut image = dynamic.to_rgba();
+        if let Rotation::Half = rotate {
+            image = imageops::rotate180(&image);
+        }
+        let mut intervals = IntervalSet::intervals_from_image(&image, axis);
+        apply_mask_file(&mut intervals, mask_path, rotate, axis);
+        match interval_func {
+            IntervalFunction::Full => (),
+            IntervalFunction::SplitEqual => interval::split_equal(
+                &mut intervals,
+                matches
+                    .value_of("num")
+                    .unwrap()
+                    .parse()
+                    .expect("num was not an integer"),
+            ),
+            #[cfg(feature = "imageproc")]
+            IntervalFunction::Edges => interval::edges_canny(
+                &mut intervals,
+                &image,
+                axis,
+                lower.parse().expect("lower was not an float"),
+                upper.parse().expect("upper was not an float"),
+            ),
+            #[cfg(feature = "rand")]
+            IntervalFunction::Random => interval::random(
+                &mut intervals,
+                lower.parse().expect("lower was not an integer"),
+                upper.parse().expect("upper was not an integer"),
+            ),
+            IntervalFunction::Threshold => interval::threshold(
+                &mut intervals,
+                &image,
+                axis,
+                lower.parse().expect("lower was not a float"),
+                upper.parse().expect("upper was not a float"),
+            ),
+        }
+        let sorting_func = sorting_mode.function::<image::Rgba<u8>>(hue_origin);
+        finish(image, axis, reverse, rotate, intervals, sorting_func, &output);
     }
+}
 
-    let upper = matches.value_of("upper").unwrap_or_default();
-    let lower = matches.value_of("lower").unwrap_or_default();
+fn apply_mask_file(
+    intervals: &mut [IntervalSet],
+    mask_path: Option<&Path>,
+    rotate: Rotation,
+    axis: pixelsort::SortAxis,
+) {
+    if let Some(mask_path) = mask_path {
+        let mut mask = image::open(mask_path).unwrap().to_luma();
+        if let Rotation::Half = rotate {
+            mask = imageops::rotate180(&mask);
+        }
+        interval::mask(intervals, &mask, axis);
+    }
+}
 
-    match interval_func {
-        IntervalFunction::Full => (),
-        IntervalFunction::SplitEqual => interval::split_equal(
-            &mut intervals,
-            matches
-                .value_of("num")
-                .unwrap()
-                .parse()
-                .expect("num was not an integer"),
-        ),
-        #[cfg(feature = "imageproc")]
-        IntervalFunction::Edges => interval::edges_canny(
-            &mut intervals,
-            &image,
-            lower.parse().expect("lower was not an float"),
-            upper.parse().expect("upper was not an float"),
-        ),
-        #[cfg(feature = "rand")]
-        IntervalFunction::Random => interval::random(
-            &mut intervals,
-            lower.parse().expect("lower was not an integer"),
-            upper.parse().expect("upper was not an integer"),
-        ),
-        IntervalFunction::Threshold => interval::threshold(
-            &mut intervals,
-            &image,
-            lower.parse().expect("lower was not a byte integer"),
-            upper.parse().expect("upper was not a byte integer"),
-        ),
-    };
-    pixelsort::sort_image(&mut image, intervals, sorting_func);
-    // rotate back
-    match rotate {
-        Rotation::Quarter => image = imageops::rotate270(&image),
-        Rotation::Half => image = imageops::rotate180(&image),
-        Rotation::NegQuarter => image = imageops::rotate90(&image),
-        Rotation::Zero => (),
+fn finish<P>(
+    mut image: image::ImageBuffer<P, Vec<P::Subpixel>>,
+    axis: pixelsort::SortAxis,
+    reverse: bool,
+    rotate: Rotation,
+    intervals: Vec<IntervalSet>,
+    sorting_func: Box<dyn SortKey<P>>,
+    output: &Path,
+) where
+    P: image::Pixel,
+    P::Subpixel: image::Primitive,
+{
+    pixelsort::sort_image(&mut image, axis, reverse, intervals, sorting_func);
+    if let Rotation::Half = rotate {
+        image = imageops::rotate180(&image);
     }
-    image.save(&output).unwrap();
+    image.save(output).unwrap();
 }
 
 fn arg_sorting() -> Arg<'static, 'static> {
@@ -210,12 +305,27 @@ fn arg_sorting() -> Arg<'static, 'static> {
         .long_help(
             "The function to use for sorting pixels.\n\
                 \n\
-                This mode defines how pixels are sorted, be it by lightness, intensity or min/maxmimum channel value of each pixel.",
+                This mode defines how pixels are sorted, be it by lightness, intensity, min/maxmimum channel value, hue or saturation of each pixel.",
         )
+        .possible_values(&["lightness", "intensity", "minimum", "maximum", "hue", "saturation"])
         .default_value("lightness")
         .takes_value(true)
 }
 
+fn arg_hue_origin() -> Arg<'static, 'static> {
+    Arg::with_name("hue_origin")
+        .long("hue-origin")
+        .help("The hue angle, in degrees, to use as the 0/360 seam when sorting by hue.")
+        .long_help(
+            "The hue angle, in degrees, to use as the 0\u{b0}/360\u{b0} seam when sorting by hue.\n\
+             \n\
+             Only used by `-s hue`; shifts the cyclic hue key so the red discontinuity falls\n\
+             outside of an otherwise smooth gradient of sorted pixels.",
+        )
+        .default_value("0")
+        .takes_value(true)
+}
+
 fn arg_num() -> Arg<'static, 'static> {
     Arg::with_name("num")
         .short("n")
@@ -255,7 +365,7 @@ fn arg_upper() -> Arg<'static, 'static> {
                 \n\
                 Required by `edge` in the range of [0.0;1140.39), accepts floating point numbers.\n\
                 Required by `random`, defines the maximum possible size of the random intervals in integers.\n\
-                Required by `threshold`, defines the upper threshold a pixels lightness has to fall below to be sorted.",
+                Required by `threshold`, defines the normalized (0.0;1.0] upper threshold a pixels lightness has to fall below to be sorted.",
         )
         .required_ifs(&[("interval_func", "edges"), ("interval_func", "threshold"), ("interval_func", "random")])
         .takes_value(true)
@@ -271,7 +381,7 @@ fn arg_lower() -> Arg<'static, 'static> {
                 \n\
                 Required by `edge` in the range of [0.0;1140.39), accepts floating point numbers.\n\
                 Required by `random`, defines the minimum possible size of the random intervals in integers.\n\
-                Required by `threshold`, defines the lower threshold a pixels lightness has to surpass to be sorted.",
+                Required by `threshold`, defines the normalized [0.0;1.0) lower threshold a pixels lightness has to surpass to be sorted.",
         )
         .required_ifs(&[("interval_func", "edges"), ("interval_func", "threshold"), ("interval_func", "random")])
         .takes_value(true)
@@ -1,33 +1,128 @@
-use image::Pixel;
+use image::{Pixel, Primitive};
+use num_traits::NumCast;
 
 #[inline]
-pub fn lightness<P>(pixel: &P) -> u32
+fn promote<S: Primitive>(value: S) -> u64 {
+    NumCast::from(value).unwrap_or(0)
+}
+
+// the subpixel's max value, promoted the same way the keys below are, so
+// callers can build SortKey bounds that scale with the image's bit depth
+#[inline]
+pub fn max_value<S: Primitive>() -> u64 {
+    promote(S::DEFAULT_MAX_VALUE)
+}
+
+#[inline]
+pub fn lightness<P>(pixel: &P) -> u64
+where
+    P: Pixel,
+    P::Subpixel: Primitive,
+{
+    promote(pixel.to_luma()[0])
+}
+
+#[inline]
+pub fn intensity<P>(pixel: &P) -> u64
 where
-    P: Pixel<Subpixel = u8>,
+    P: Pixel,
+    P::Subpixel: Primitive,
 {
-    pixel.to_luma()[0] as u32
+    pixel.channels().iter().copied().map(promote).sum()
 }
 
 #[inline]
-pub fn intensity<P>(pixel: &P) -> u32
+pub fn chan_min<P>(pixel: &P) -> u64
+where
+    P: Pixel,
+    P::Subpixel: Primitive,
+{
+    let min = pixel
+        .channels()
+        .iter()
+        .copied()
+        .fold(P::Subpixel::DEFAULT_MAX_VALUE, |acc, c| if c < acc { c } else { acc });
+    promote(min)
+}
+
+#[inline]
+pub fn chan_max<P>(pixel: &P) -> u64
+where
+    P: Pixel,
+    P::Subpixel: Primitive,
+{
+    let max = pixel
+        .channels()
+        .iter()
+        .copied()
+        .fold(P::Subpixel::DEFAULT_MIN_VALUE, |acc, c| if c > acc { c } else { acc });
+    promote(max)
+}
+
+// ten buckets per degree gives smoother gradients than a plain 0..=359 scale
+const HUE_BUCKETS: u64 = 3600;
+
+// assumes channels 0/1/2 are R/G/B in that order, which holds for
+// image::Rgb(a)-family pixels but not e.g. a Bgr(a) one
+#[inline]
+fn rgb_normalized<P>(pixel: &P) -> [f32; 3]
 where
-    P: Pixel<Subpixel = u8>,
+    P: Pixel,
+    P::Subpixel: Primitive,
 {
-    pixel.channels().iter().map(|c| *c as u32).sum()
+    let max_value = promote(P::Subpixel::DEFAULT_MAX_VALUE) as f32;
+    let channels = pixel.channels();
+    // Luma/LumaA have no g/b of their own and channel 1 (if present) is
+    // alpha, not green; treat them as gray rather than reading it as color.
+    if channels.len() < 3 {
+        let luma = promote(channels[0]) as f32 / max_value;
+        return [luma, luma, luma];
+    }
+    [
+        promote(channels[0]) as f32 / max_value,
+        promote(channels[1]) as f32 / max_value,
+        promote(channels[2]) as f32 / max_value,
+    ]
 }
 
+// hue is cyclic, so `origin_degrees` rotates where the 0/360 seam falls,
+// letting callers avoid slicing a hard discontinuity through a smooth
+// gradient; see `rgb_normalized`'s comment for the channel-order assumption
 #[inline]
-pub fn chan_min<P>(pixel: &P) -> u32
+pub fn hue<P>(pixel: &P, origin_degrees: u32) -> u64
 where
-    P: Pixel<Subpixel = u8>,
+    P: Pixel,
+    P::Subpixel: Primitive,
 {
-    pixel.channels().iter().copied().min().unwrap_or(0) as u32
+    let [r, g, b] = rgb_normalized(pixel);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+    let degrees = if delta <= f32::EPSILON {
+        0.0
+    } else if max == r {
+        60.0 * (((g - b) / delta).rem_euclid(6.0))
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let bucket = (degrees * (HUE_BUCKETS as f32 / 360.0)).round() as i64;
+    let origin = origin_degrees as i64 * HUE_BUCKETS as i64 / 360;
+    (bucket - origin).rem_euclid(HUE_BUCKETS as i64) as u64
 }
 
+// 0..=255 regardless of the image's subpixel bit depth; see
+// `rgb_normalized`'s comment for the channel-order assumption
 #[inline]
-pub fn chan_max<P>(pixel: &P) -> u32
+pub fn saturation<P>(pixel: &P) -> u64
 where
-    P: Pixel<Subpixel = u8>,
+    P: Pixel,
+    P::Subpixel: Primitive,
 {
-    pixel.channels().iter().copied().max().unwrap_or(255) as u32
+    let [r, g, b] = rgb_normalized(pixel);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let saturation = if max <= f32::EPSILON { 0.0 } else { (max - min) / max };
+    (saturation * 255.0).round() as u64
 }